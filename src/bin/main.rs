@@ -1,9 +1,12 @@
-use math_images_processor::{process_directory, process_image_file, ImageProcessorConfig};
+use math_images_processor::{
+    output_file_path, process_directory, process_image_file, ImageProcessorConfig,
+};
 use std::env;
 use std::fs;
 use std::path::PathBuf;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Collect command-line arguments
     let args: Vec<String> = env::args().collect();
 
@@ -26,11 +29,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Check if the input path is a file or a directory
     if input_path.is_file() {
         println!("Processing single file: {}", input_path.display());
-        let output_path = output_dir.join(input_path.file_name().unwrap());
+        let output_path = output_file_path(&output_dir, &input_path, config.output_format);
         process_image_file(&input_path, &output_path, &config)?;
     } else if input_path.is_dir() {
         println!("Processing directory: {}", input_path.display());
-        process_directory(&input_path, &output_dir, &config)?;
+        process_directory(&input_path, &output_dir, &config).await?;
     } else {
         eprintln!("Error: Provided path is neither a file nor a directory.");
         return Ok(());
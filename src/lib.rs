@@ -1,12 +1,85 @@
-use image::{DynamicImage, GenericImageView, GrayImage, Luma};
+use image::{DynamicImage, GenericImageView, GrayImage, ImageFormat, Luma};
+use sha2::{Digest, Sha256};
 use std::path::{Path, PathBuf};
 
+/// Selects the image format `process_image_file`/`process_directory` encode their output as.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Bmp,
+    Tiff,
+}
+
+impl OutputFormat {
+    /// The `image` crate format this variant encodes to.
+    fn image_format(self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::WebP => ImageFormat::WebP,
+            OutputFormat::Bmp => ImageFormat::Bmp,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+        }
+    }
+
+    /// The file extension (without a leading dot) output files are saved with.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Tiff => "tiff",
+        }
+    }
+}
+
+/// Selects how a grayscale image is binarized before the crop/resize stages.
+#[derive(Clone, Debug)]
+pub enum ThresholdMode {
+    /// The original fixed-cutoff contrast enhancement (`>200 -> 255`, else `l/2`).
+    Fixed,
+    /// Otsu's method: picks a single global threshold that maximizes between-class variance.
+    Otsu,
+    /// Sauvola's method: a local threshold derived from the mean and standard deviation of a
+    /// square window around each pixel, suited to unevenly-lit scans. `window` is the side
+    /// length in pixels (odd values recommended, e.g. 15) and `k` tunes sensitivity (~0.5).
+    Sauvola { window: u32, k: f64 },
+}
+
 /// Configuration for image processing final sizes
 #[derive(Clone)]
 pub struct ImageProcessorConfig {
     pub width: u32,
     pub height: u32,
     pub border: u32,
+    pub threshold: ThresholdMode,
+    /// When `true`, estimates and corrects small rotations before cropping. See `preprocess_image`.
+    pub deskew: bool,
+    /// The format `process_image_file`/`process_directory` encode output files as.
+    pub output_format: OutputFormat,
+    /// File extensions (lowercase, without a leading dot) that `process_directory` and
+    /// `process_directory_without_async` will pick up as input images.
+    pub input_extensions: Vec<String>,
+    /// When `true`, `process_image_file` looks up/stores results in `cache_dir` keyed by a
+    /// digest of the input bytes and the config fields that affect the output (see `cache_key`;
+    /// `output_format` selects the cached file's extension), skipping reprocessing on a cache hit.
+    pub cache_enabled: bool,
+    /// Directory cached outputs are read from and written to. Only consulted when `cache_enabled`.
+    pub cache_dir: PathBuf,
+    /// When `true`, runs a 3x3 median filter over the binarized image to kill isolated
+    /// salt-and-pepper specks before `crop_white_borders` locks onto the bounding box.
+    pub denoise: bool,
+    /// When `true`, follows the (optional) median filter with a morphological opening
+    /// (3x3 erosion then dilation) to remove thin stray marks. Ink strokes at least 3 pixels
+    /// wide keep their shape; any dark stroke narrower than that is removed as well.
+    pub morphological_opening: bool,
+    /// Thread count `process_directory_parallel` builds its rayon pool with. `None` uses
+    /// rayon's global default pool (one thread per core).
+    #[cfg(feature = "parallel")]
+    pub parallel_threads: Option<usize>,
 }
 
 /// Default configuration for image processing final sizes
@@ -16,6 +89,24 @@ impl Default for ImageProcessorConfig {
             width: 300,
             height: 100,
             border: 5,
+            threshold: ThresholdMode::Fixed,
+            deskew: false,
+            output_format: OutputFormat::Png,
+            input_extensions: vec![
+                "png".to_string(),
+                "jpg".to_string(),
+                "jpeg".to_string(),
+                "webp".to_string(),
+                "bmp".to_string(),
+                "tiff".to_string(),
+                "tif".to_string(),
+            ],
+            cache_enabled: false,
+            cache_dir: PathBuf::from(".cache"),
+            denoise: false,
+            morphological_opening: false,
+            #[cfg(feature = "parallel")]
+            parallel_threads: None,
         }
     }
 }
@@ -32,7 +123,21 @@ pub fn preprocess_image(
         gray_img = invert_colors(&gray_img);
     }
 
-    let cropped_img = crop_white_borders(&enhance_contrast(&gray_img));
+    if config.deskew {
+        gray_img = deskew(&gray_img, &config.threshold);
+    }
+
+    let mut thresholded_img = apply_threshold(&gray_img, &config.threshold);
+
+    if config.denoise {
+        thresholded_img = median_denoise(&thresholded_img);
+    }
+
+    if config.morphological_opening {
+        thresholded_img = morphological_open(&thresholded_img);
+    }
+
+    let cropped_img = crop_white_borders(&thresholded_img);
 
     let final_img = fit_into_canvas(&cropped_img, config.width, config.height, config.border);
 
@@ -40,6 +145,9 @@ pub fn preprocess_image(
 }
 
 /// Processes a single image file, specified by `input_path`, and saves the processed image to a new file, specified by `output_path`.
+/// The output is encoded explicitly as `config.output_format`, regardless of `output_path`'s extension.
+/// When `config.cache_enabled` is set, a previously cached output for the same input bytes and
+/// config is reused instead of reprocessing; see `cache_key`.
 /// Final image sizes can be configured via `ImageProcessorConfig`.
 /// For processing a directory use `process_directory`.
 /// For processing a `DynamicImage` from the `image` crate, use directly `preprocess_image`.
@@ -48,13 +156,75 @@ pub fn process_image_file(
     output_path: &Path,
     config: &ImageProcessorConfig,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if config.cache_enabled {
+        return process_image_file_cached(input_path, output_path, config);
+    }
+
     let img = image::open(input_path)?;
     let processed_img = preprocess_image(img, config)?;
-    processed_img.save(output_path)?;
+    processed_img.save_with_format(output_path, config.output_format.image_format())?;
     Ok(())
 }
 
-/// Processes all PNG, JPG, and JPEG files in a directory and saves the processed images in a separate directory.
+/// Cache-aware path used by `process_image_file` when `config.cache_enabled` is set. Looks up
+/// `config.cache_dir` for a file named after `cache_key(input_bytes, config)`; on a hit, copies
+/// it straight to `output_path`, otherwise processes normally and populates the cache entry.
+fn process_image_file_cached(
+    input_path: &Path,
+    output_path: &Path,
+    config: &ImageProcessorConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(&config.cache_dir)?;
+
+    let input_bytes = std::fs::read(input_path)?;
+    let cache_path = config
+        .cache_dir
+        .join(cache_key(&input_bytes, config))
+        .with_extension(config.output_format.extension());
+
+    if cache_path.exists() {
+        std::fs::copy(&cache_path, output_path)?;
+        return Ok(());
+    }
+
+    let img = image::load_from_memory(&input_bytes)?;
+    let processed_img = preprocess_image(img, config)?;
+
+    // Encode to a thread-unique temp file first, then rename it into place atomically, so
+    // concurrent callers racing on the same cache key (e.g. via process_directory_parallel)
+    // never observe or copy a partially-written cache entry.
+    let temp_path = cache_path.with_extension(format!(
+        "{}.tmp.{:?}",
+        config.output_format.extension(),
+        std::thread::current().id()
+    ));
+    processed_img.save_with_format(&temp_path, config.output_format.image_format())?;
+    std::fs::rename(&temp_path, &cache_path)?;
+
+    std::fs::copy(&cache_path, output_path)?;
+
+    Ok(())
+}
+
+/// Computes a content-addressed cache key from the input file's bytes and the config fields
+/// that affect the processed output, so changing the config invalidates stale cache entries.
+fn cache_key(input_bytes: &[u8], config: &ImageProcessorConfig) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(input_bytes);
+    hasher.update(config.width.to_le_bytes());
+    hasher.update(config.height.to_le_bytes());
+    hasher.update(config.border.to_le_bytes());
+    hasher.update(format!("{:?}", config.threshold).as_bytes());
+    hasher.update([
+        config.deskew as u8,
+        config.denoise as u8,
+        config.morphological_opening as u8,
+    ]);
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Processes every file in a directory whose extension matches `config.input_extensions` and saves the processed images in a separate directory.
 /// NOTE 1: This uses asynchronous processing, so use with tokio macros in the main function.
 /// NOTE 2: Asynchronous processing is almost 7x faster than synchronous processing. For synchronous processing use `process_directory_without_async`.
 /// Final image sizes can be configured via `ImageProcessorConfig`.
@@ -79,9 +249,9 @@ pub async fn process_directory(
             if ext
                 .to_str()
                 .map(|s| s.to_lowercase())
-                .map_or(false, |ext| matches!(ext.as_str(), "png" | "jpg" | "jpeg"))
+                .is_some_and(|ext| config.input_extensions.iter().any(|e| e == &ext))
             {
-                let output_file = output_dir.join(path.file_name().unwrap());
+                let output_file = output_file_path(output_dir, &path, config.output_format);
                 let cloned_config = config.clone();
 
                 // Spawn a task for each image processing operation
@@ -119,9 +289,9 @@ pub fn process_directory_without_async(
             if ext
                 .to_str()
                 .map(|s| s.to_lowercase())
-                .map_or(false, |ext| matches!(ext.as_str(), "png" | "jpg" | "jpeg"))
+                .is_some_and(|ext| config.input_extensions.iter().any(|e| e == &ext))
             {
-                let output_file = output_dir.join(path.file_name().unwrap());
+                let output_file = output_file_path(output_dir, &path, config.output_format);
                 process_image_file(&path, &output_file, config)?;
             }
         }
@@ -130,6 +300,107 @@ pub fn process_directory_without_async(
     Ok(())
 }
 
+/// The result of `process_directory_parallel` when one or more files failed: each entry pairs
+/// the input path with the error `process_image_file` returned for it, or, when the output
+/// directory could not be created, the input directory could not be read or the thread pool
+/// could not be built, the directory concerned with that error.
+#[derive(Debug)]
+pub struct DirectoryProcessingErrors(pub Vec<(PathBuf, String)>);
+
+impl std::fmt::Display for DirectoryProcessingErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} file(s) failed to process:", self.0.len())?;
+        for (path, error) in &self.0 {
+            writeln!(f, "  {}: {}", path.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DirectoryProcessingErrors {}
+
+/// Processes every file in a directory whose extension matches `config.input_extensions` using
+/// a rayon parallel iterator, instead of the tokio task-per-file model of `process_directory`.
+/// This gives library consumers who aren't building async applications a runtime-free way to
+/// saturate all cores. Concurrency is bounded by `config.parallel_threads` (falling back to
+/// rayon's global default pool when `None`) rather than spawning one task per file. Per-file
+/// errors are aggregated into `DirectoryProcessingErrors` instead of being printed.
+#[cfg(feature = "parallel")]
+pub fn process_directory_parallel(
+    input_dir: &Path,
+    output_dir: &Path,
+    config: &ImageProcessorConfig,
+) -> Result<(), DirectoryProcessingErrors> {
+    use rayon::prelude::*;
+
+    if !output_dir.exists() {
+        std::fs::create_dir_all(output_dir)
+            .map_err(|e| DirectoryProcessingErrors(vec![(output_dir.to_path_buf(), e.to_string())]))?;
+    }
+
+    let entries = std::fs::read_dir(input_dir)
+        .map_err(|e| DirectoryProcessingErrors(vec![(input_dir.to_path_buf(), e.to_string())]))?;
+
+    let mut entry_errors = vec![];
+    let paths: Vec<PathBuf> = entries
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.path()),
+            Err(e) => {
+                entry_errors.push((input_dir.to_path_buf(), e.to_string()));
+                None
+            }
+        })
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .map(|s| s.to_lowercase())
+                .is_some_and(|ext| config.input_extensions.iter().any(|e| e == &ext))
+        })
+        .collect();
+
+    let process_paths = |paths: &[PathBuf]| -> Vec<(PathBuf, String)> {
+        paths
+            .par_iter()
+            .filter_map(|path| {
+                let output_file = output_file_path(output_dir, path, config.output_format);
+
+                process_image_file(path, &output_file, config)
+                    .err()
+                    .map(|e| (path.clone(), e.to_string()))
+            })
+            .collect()
+    };
+
+    let mut errors = entry_errors;
+    errors.extend(match config.parallel_threads {
+        Some(num_threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|e| DirectoryProcessingErrors(vec![(input_dir.to_path_buf(), e.to_string())]))?
+            .install(|| process_paths(&paths)),
+        None => process_paths(&paths),
+    });
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(DirectoryProcessingErrors(errors))
+    }
+}
+
+/// Builds the output path for `original_path` inside `output_dir`, keeping `original_path`'s
+/// full original file name (including its extension) and appending `format`'s extension, rather
+/// than replacing the original extension. Two inputs in the same directory can never collide on
+/// this, since the filesystem already guarantees their full file names are distinct, whereas
+/// replacing the extension outright would collide for inputs sharing a stem (e.g. `formula.png`
+/// and `formula.jpg`).
+pub fn output_file_path(output_dir: &Path, original_path: &Path, format: OutputFormat) -> PathBuf {
+    let mut file_name = original_path.file_name().unwrap().to_os_string();
+    file_name.push(".");
+    file_name.push(format.extension());
+    output_dir.join(file_name)
+}
+
 /// Checks if the image is inverted (fonts are white on a black background)
 fn is_inverted(img: &GrayImage) -> bool {
     let (mut black_count, mut white_count) = (0, 0);
@@ -170,6 +441,304 @@ fn enhance_contrast(img: &GrayImage) -> GrayImage {
     enhanced
 }
 
+/// Computes a global binarization threshold using Otsu's method: the threshold in `0..256`
+/// that maximizes the between-class variance of the image's grayscale histogram.
+fn otsu_threshold(img: &GrayImage) -> u8 {
+    let mut histogram = [0u32; 256];
+    for pixel in img.pixels() {
+        let Luma([l]) = *pixel;
+        histogram[l as usize] += 1;
+    }
+
+    let total = img.pixels().len() as f64;
+
+    let sum_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| i as f64 * count as f64)
+        .sum();
+
+    let mut best_threshold = 0u8;
+    let mut best_variance = 0f64;
+
+    let mut sum_below = 0f64;
+    let mut count_below = 0f64;
+
+    for (t, &count) in histogram.iter().enumerate() {
+        let w0 = count_below / total;
+        let w1 = 1.0 - w0;
+
+        if w0 > 0.0 && w1 > 0.0 {
+            let mean0 = sum_below / (w0 * total);
+            let mean1 = (sum_total - sum_below) / (w1 * total);
+            let variance = w0 * w1 * (mean0 - mean1).powi(2);
+
+            if variance > best_variance {
+                best_variance = variance;
+                best_threshold = t as u8;
+            }
+        }
+
+        sum_below += t as f64 * count as f64;
+        count_below += count as f64;
+    }
+
+    best_threshold
+}
+
+/// Sauvola's local adaptive threshold: `T = m * (1 + k * (s / R - 1))` with `R = 128`, where `m`
+/// and `s` are the mean and standard deviation of a `window`x`window` square around each pixel.
+fn sauvola_binarize(img: &GrayImage, window: u32, k: f64) -> GrayImage {
+    const R: f64 = 128.0;
+
+    let (width, height) = img.dimensions();
+    let (sum_integral, sum_sq_integral) = build_integral_images(img);
+
+    let half = (window / 2).max(1);
+    let stride = (width + 1) as usize;
+    let mut binarized = img.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let x0 = x.saturating_sub(half);
+            let y0 = y.saturating_sub(half);
+            let x1 = (x + half).min(width - 1);
+            let y1 = (y + half).min(height - 1);
+
+            let count = ((x1 - x0 + 1) * (y1 - y0 + 1)) as f64;
+            let sum = region_sum(&sum_integral, stride, x0, y0, x1, y1);
+            let sum_sq = region_sum(&sum_sq_integral, stride, x0, y0, x1, y1);
+
+            let mean = sum / count;
+            let variance = (sum_sq / count) - (mean * mean);
+            let std_dev = variance.max(0.0).sqrt();
+
+            let threshold = mean * (1.0 + k * (std_dev / R - 1.0));
+
+            let Luma([l]) = img.get_pixel(x, y);
+            binarized.put_pixel(
+                x,
+                y,
+                if (*l as f64) < threshold {
+                    Luma([0])
+                } else {
+                    Luma([255])
+                },
+            );
+        }
+    }
+
+    binarized
+}
+
+/// Builds summed-area tables of pixel values and squared pixel values, padded by one row and
+/// one column of zeros so `region_sum` can use inclusive `(x0, y0)..=(x1, y1)` ranges.
+fn build_integral_images(img: &GrayImage) -> (Vec<f64>, Vec<f64>) {
+    let (width, height) = img.dimensions();
+    let stride = (width + 1) as usize;
+    let mut sum = vec![0f64; stride * (height as usize + 1)];
+    let mut sum_sq = vec![0f64; stride * (height as usize + 1)];
+
+    for y in 0..height {
+        for x in 0..width {
+            let Luma([l]) = img.get_pixel(x, y);
+            let value = *l as f64;
+
+            let idx = (y as usize + 1) * stride + (x as usize + 1);
+            let up = (y as usize) * stride + (x as usize + 1);
+            let left = (y as usize + 1) * stride + (x as usize);
+            let up_left = (y as usize) * stride + (x as usize);
+
+            sum[idx] = value + sum[up] + sum[left] - sum[up_left];
+            sum_sq[idx] = value * value + sum_sq[up] + sum_sq[left] - sum_sq[up_left];
+        }
+    }
+
+    (sum, sum_sq)
+}
+
+/// Reads the sum over the inclusive pixel range `(x0, y0)..=(x1, y1)` from an integral image
+/// built by `build_integral_images`, given the image's `stride` (width + 1).
+fn region_sum(integral: &[f64], stride: usize, x0: u32, y0: u32, x1: u32, y1: u32) -> f64 {
+    let (x0, y0, x1, y1) = (x0 as usize, y0 as usize, x1 as usize, y1 as usize);
+
+    let bottom_right = integral[(y1 + 1) * stride + (x1 + 1)];
+    let top_right = integral[y0 * stride + (x1 + 1)];
+    let bottom_left = integral[(y1 + 1) * stride + x0];
+    let top_left = integral[y0 * stride + x0];
+
+    bottom_right - top_right - bottom_left + top_left
+}
+
+/// Maps every pixel to pure black or white around the given threshold
+fn binarize(img: &GrayImage, threshold: u8) -> GrayImage {
+    let mut binarized = img.clone();
+    for pixel in binarized.pixels_mut() {
+        let Luma([l]) = *pixel;
+        *pixel = if l < threshold { Luma([0]) } else { Luma([255]) };
+    }
+    binarized
+}
+
+/// Binarizes `img` per `mode`. Shared by `preprocess_image` and `deskew`.
+fn apply_threshold(img: &GrayImage, mode: &ThresholdMode) -> GrayImage {
+    match mode {
+        ThresholdMode::Fixed => enhance_contrast(img),
+        ThresholdMode::Otsu => binarize(img, otsu_threshold(img)),
+        ThresholdMode::Sauvola { window, k } => sauvola_binarize(img, *window, *k),
+    }
+}
+
+/// Estimates and corrects small rotations using the projection-profile method, scoring candidate
+/// angles against `img` binarized per `threshold`.
+fn deskew(img: &GrayImage, threshold: &ThresholdMode) -> GrayImage {
+    let binarized = apply_threshold(img, threshold);
+    let angle = estimate_skew_angle(&binarized);
+    if angle == 0.0 {
+        return img.clone();
+    }
+
+    rotate_image(img, angle, Luma([255]))
+}
+
+/// Searches `-15.0..=15.0` degrees in `0.5`-degree steps for the rotation with the highest
+/// row-wise variance in dark-pixel count, since correctly-oriented rows align sharply. Ties
+/// (e.g. a degenerate all-zero profile) resolve to 0 degrees rather than whichever angle happens
+/// to be swept first.
+fn estimate_skew_angle(img: &GrayImage) -> f64 {
+    let mut best_angle = 0.0;
+    let mut best_score = projection_profile_variance(img);
+
+    let mut angle = -15.0;
+    while angle <= 15.0 {
+        if angle != 0.0 {
+            let rotated = rotate_image(img, angle, Luma([255]));
+            let score = projection_profile_variance(&rotated);
+
+            if score > best_score {
+                best_score = score;
+                best_angle = angle;
+            }
+        }
+
+        angle += 0.5;
+    }
+
+    best_angle
+}
+
+/// Computes the variance, across rows, of the count of dark (`l < 128`) pixels per row.
+fn projection_profile_variance(img: &GrayImage) -> f64 {
+    let (width, height) = img.dimensions();
+
+    let profile: Vec<f64> = (0..height)
+        .map(|y| {
+            (0..width)
+                .filter(|&x| {
+                    let Luma([l]) = img.get_pixel(x, y);
+                    *l < 128
+                })
+                .count() as f64
+        })
+        .collect();
+
+    let mean = profile.iter().sum::<f64>() / profile.len() as f64;
+
+    profile.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / profile.len() as f64
+}
+
+/// Rotates the image by `angle_degrees` around its center using nearest-neighbor sampling,
+/// filling any pixel whose source falls outside the original bounds with `fill`.
+fn rotate_image(img: &GrayImage, angle_degrees: f64, fill: Luma<u8>) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let (center_x, center_y) = (width as f64 / 2.0, height as f64 / 2.0);
+
+    // Negate the angle and map destination -> source, so the image itself rotates by
+    // `angle_degrees` rather than the sampling grid.
+    let (sin_t, cos_t) = (-angle_degrees.to_radians()).sin_cos();
+
+    let mut rotated = GrayImage::from_pixel(width, height, fill);
+
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f64 - center_x;
+            let dy = y as f64 - center_y;
+
+            let src_x = center_x + dx * cos_t - dy * sin_t;
+            let src_y = center_y + dx * sin_t + dy * cos_t;
+
+            if src_x >= 0.0 && src_y >= 0.0 {
+                let (sx, sy) = (src_x.round() as u32, src_y.round() as u32);
+                if sx < width && sy < height {
+                    rotated.put_pixel(x, y, *img.get_pixel(sx, sy));
+                }
+            }
+        }
+    }
+
+    rotated
+}
+
+/// Replaces each pixel with the median of its edge-clamped 3x3 neighborhood, killing isolated
+/// salt-and-pepper specks without blurring larger ink strokes.
+fn median_denoise(img: &GrayImage) -> GrayImage {
+    map_3x3(img, |mut neighborhood| {
+        neighborhood.sort_unstable();
+        neighborhood[4]
+    })
+}
+
+/// Morphological opening (erosion then dilation) with a 3x3 structuring element, removing thin
+/// stray marks left over after denoising. Dark regions at least 3x3 pixels across are restored
+/// to their shape; strokes narrower than 3 pixels are removed along with the stray marks.
+fn morphological_open(img: &GrayImage) -> GrayImage {
+    dilate_3x3(&erode_3x3(img))
+}
+
+/// Erodes dark ink regions: each pixel becomes the brightest value in its 3x3 neighborhood.
+fn erode_3x3(img: &GrayImage) -> GrayImage {
+    map_3x3(img, |neighborhood| *neighborhood.iter().max().unwrap())
+}
+
+/// Dilates dark ink regions: each pixel becomes the darkest value in its 3x3 neighborhood.
+fn dilate_3x3(img: &GrayImage) -> GrayImage {
+    map_3x3(img, |neighborhood| *neighborhood.iter().min().unwrap())
+}
+
+/// Applies `reduce` to the edge-clamped 3x3 neighborhood of every pixel, shared by the
+/// median/erosion/dilation passes above.
+fn map_3x3(img: &GrayImage, reduce: impl Fn([u8; 9]) -> u8) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let mut output = img.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            output.put_pixel(x, y, Luma([reduce(neighborhood_3x3(img, x, y))]));
+        }
+    }
+
+    output
+}
+
+/// Gathers the edge-clamped 3x3 neighborhood of pixel `(x, y)`, row-major.
+fn neighborhood_3x3(img: &GrayImage, x: u32, y: u32) -> [u8; 9] {
+    let (width, height) = img.dimensions();
+    let mut neighborhood = [0u8; 9];
+    let mut i = 0;
+
+    for dy in -1..=1i32 {
+        for dx in -1..=1i32 {
+            let nx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+            let ny = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+            let Luma([l]) = img.get_pixel(nx, ny);
+            neighborhood[i] = *l;
+            i += 1;
+        }
+    }
+
+    neighborhood
+}
+
 /// Crops the white borders of the image till the black font
 fn crop_white_borders(img: &GrayImage) -> GrayImage {
     let (width, height) = img.dimensions();
@@ -223,12 +792,7 @@ fn fit_into_canvas(img: &GrayImage, width: u32, height: u32, border: u32) -> Gra
     let new_height = (img_height as f64 * scale).round() as u32;
 
     // Resize the formula image
-    let resized_img = image::imageops::resize(
-        img,
-        new_width,
-        new_height,
-        image::imageops::FilterType::Lanczos3,
-    );
+    let resized_img = resize_cropped(img, new_width, new_height);
 
     // Create a white canvas
     let mut canvas = GrayImage::from_pixel(width, height, Luma([255]));
@@ -242,3 +806,238 @@ fn fit_into_canvas(img: &GrayImage, width: u32, height: u32, border: u32) -> Gra
 
     canvas
 }
+
+/// Downscales `img` to `new_width`x`new_height`. With the `fast-resize` feature enabled, this
+/// routes through a SIMD-accelerated resizer; otherwise it falls back to `image`'s pure-Rust
+/// Lanczos3 resize, which stays the bit-reproducible default.
+#[cfg(not(feature = "fast-resize"))]
+fn resize_cropped(img: &GrayImage, new_width: u32, new_height: u32) -> GrayImage {
+    image::imageops::resize(
+        img,
+        new_width,
+        new_height,
+        image::imageops::FilterType::Lanczos3,
+    )
+}
+
+/// SIMD-accelerated counterpart of `resize_cropped`, backed by `fast_image_resize`. Only the
+/// per-image cost of the resize itself changes; inputs/outputs stay plain `GrayImage`s.
+/// `fast_image_resize` rejects zero-sized images, so a degenerate target (an elongated crop that
+/// rounds one dimension to 0) falls back to the default path, which tolerates it.
+#[cfg(feature = "fast-resize")]
+fn resize_cropped(img: &GrayImage, new_width: u32, new_height: u32) -> GrayImage {
+    use fast_image_resize::images::Image as FrImage;
+    use fast_image_resize::{PixelType, ResizeOptions, Resizer};
+
+    if new_width == 0 || new_height == 0 {
+        return image::imageops::resize(
+            img,
+            new_width,
+            new_height,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
+
+    let (width, height) = img.dimensions();
+    let src_image = FrImage::from_vec_u8(width, height, img.clone().into_raw(), PixelType::U8)
+        .expect("GrayImage's buffer always matches its own declared dimensions");
+
+    let mut dst_image = FrImage::new(new_width, new_height, PixelType::U8);
+
+    Resizer::new()
+        .resize(&src_image, &mut dst_image, &ResizeOptions::default())
+        .expect("U8 -> U8 resize with matching pixel types never fails");
+
+    GrayImage::from_raw(new_width, new_height, dst_image.into_vec())
+        .expect("resizer output buffer is sized for new_width x new_height")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> GrayImage {
+        GrayImage::from_pixel(width, height, Luma([value]))
+    }
+
+    #[test]
+    fn otsu_threshold_splits_bimodal_histogram() {
+        // Half the image near-black, half near-white: the threshold should land between them.
+        let mut img = GrayImage::new(20, 10);
+        for y in 0..10 {
+            for x in 0..20 {
+                let value = if x < 10 { 10 } else { 245 };
+                img.put_pixel(x, y, Luma([value]));
+            }
+        }
+
+        let threshold = otsu_threshold(&img);
+        assert!(threshold > 10 && threshold < 245);
+    }
+
+    #[test]
+    fn otsu_threshold_on_uniform_image_does_not_panic() {
+        let img = solid(5, 5, 128);
+        // Every candidate t has w0 == 0 or w1 == 0, so best_threshold should stay at its default.
+        assert_eq!(otsu_threshold(&img), 0);
+    }
+
+    #[test]
+    fn sauvola_binarize_keeps_dark_blob_on_light_background() {
+        let mut img = solid(30, 30, 230);
+        for y in 12..18 {
+            for x in 12..18 {
+                img.put_pixel(x, y, Luma([20]));
+            }
+        }
+
+        let binarized = sauvola_binarize(&img, 15, 0.5);
+
+        let Luma([center]) = *binarized.get_pixel(15, 15);
+        let Luma([corner]) = *binarized.get_pixel(1, 1);
+        assert_eq!(center, 0);
+        assert_eq!(corner, 255);
+    }
+
+    #[test]
+    fn region_sum_matches_brute_force_sum() {
+        let img = GrayImage::from_fn(8, 6, |x, y| Luma([((x + y) % 7) as u8]));
+        let (integral, _) = build_integral_images(&img);
+        let stride = (img.width() + 1) as usize;
+
+        let (x0, y0, x1, y1) = (2, 1, 5, 4);
+        let expected: u32 = (y0..=y1)
+            .flat_map(|y| (x0..=x1).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let Luma([l]) = *img.get_pixel(x, y);
+                l as u32
+            })
+            .sum();
+
+        assert_eq!(region_sum(&integral, stride, x0, y0, x1, y1), expected as f64);
+    }
+
+    #[test]
+    fn rotate_image_by_zero_degrees_is_identity() {
+        let img = GrayImage::from_fn(9, 7, |x, y| Luma([((x * 3 + y) % 251) as u8]));
+        let rotated = rotate_image(&img, 0.0, Luma([255]));
+        assert_eq!(rotated, img);
+    }
+
+    #[test]
+    fn estimate_skew_angle_is_zero_for_a_flat_uniform_image() {
+        // No dark pixels at any angle: the degenerate all-zero profile must not win on a tie.
+        let img = solid(40, 40, 255);
+        assert_eq!(estimate_skew_angle(&img), 0.0);
+    }
+
+    #[test]
+    fn median_denoise_removes_isolated_speck() {
+        let mut img = solid(5, 5, 255);
+        img.put_pixel(2, 2, Luma([0]));
+
+        let denoised = median_denoise(&img);
+
+        let Luma([center]) = *denoised.get_pixel(2, 2);
+        assert_eq!(center, 255);
+    }
+
+    #[test]
+    fn morphological_open_removes_thin_speck_but_keeps_larger_blob() {
+        let mut img = solid(20, 20, 255);
+        img.put_pixel(1, 1, Luma([0])); // isolated 1px speck
+
+        for y in 8..14 {
+            for x in 8..14 {
+                img.put_pixel(x, y, Luma([0])); // solid 6x6 blob
+            }
+        }
+
+        let opened = morphological_open(&img);
+
+        let Luma([speck]) = *opened.get_pixel(1, 1);
+        let Luma([blob_center]) = *opened.get_pixel(10, 10);
+        assert_eq!(speck, 255);
+        assert_eq!(blob_center, 0);
+    }
+
+    #[test]
+    fn cache_key_is_deterministic_and_sensitive_to_config() {
+        let bytes = b"fake image bytes";
+        let config = ImageProcessorConfig::default();
+
+        assert_eq!(cache_key(bytes, &config), cache_key(bytes, &config));
+
+        let mut deskewed = config.clone();
+        deskewed.deskew = true;
+        assert_ne!(cache_key(bytes, &config), cache_key(bytes, &deskewed));
+
+        let mut resized = config.clone();
+        resized.width = config.width + 1;
+        assert_ne!(cache_key(bytes, &config), cache_key(bytes, &resized));
+    }
+
+    #[test]
+    fn output_file_path_keeps_distinct_names_for_same_stem_different_extension() {
+        let output_dir = Path::new("out");
+        let png_path =
+            output_file_path(output_dir, Path::new("formula.png"), OutputFormat::Jpeg);
+        let jpg_path =
+            output_file_path(output_dir, Path::new("formula.jpg"), OutputFormat::Jpeg);
+
+        assert_ne!(png_path, jpg_path);
+    }
+
+    #[cfg(feature = "fast-resize")]
+    #[test]
+    fn resize_cropped_matches_target_dimensions() {
+        let img = solid(8, 8, 200);
+        let resized = resize_cropped(&img, 4, 2);
+        assert_eq!(resized.dimensions(), (4, 2));
+    }
+
+    #[cfg(feature = "fast-resize")]
+    #[test]
+    fn resize_cropped_falls_back_for_zero_sized_target() {
+        let img = solid(8, 8, 200);
+        let resized = resize_cropped(&img, 0, 4);
+        assert_eq!(resized.dimensions(), (0, 4));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn process_directory_parallel_aggregates_errors_but_still_processes_good_files() {
+        let input_dir = std::env::temp_dir().join(format!(
+            "math_images_processor_test_input_{:?}",
+            std::thread::current().id()
+        ));
+        let output_dir = std::env::temp_dir().join(format!(
+            "math_images_processor_test_output_{:?}",
+            std::thread::current().id()
+        ));
+        let _ = std::fs::remove_dir_all(&input_dir);
+        let _ = std::fs::remove_dir_all(&output_dir);
+        std::fs::create_dir_all(&input_dir).unwrap();
+
+        let mut good_image = solid(10, 10, 255);
+        good_image.put_pixel(5, 5, Luma([0]));
+        good_image
+            .save_with_format(input_dir.join("good.png"), ImageFormat::Png)
+            .unwrap();
+        std::fs::write(input_dir.join("bad.png"), b"not a png").unwrap();
+
+        let config = ImageProcessorConfig::default();
+        let result = process_directory_parallel(&input_dir, &output_dir, &config);
+
+        let errors = result.expect_err("the corrupt file should surface as an error").0;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, input_dir.join("bad.png"));
+
+        let expected_good_output =
+            output_file_path(&output_dir, &input_dir.join("good.png"), config.output_format);
+        assert!(expected_good_output.exists());
+
+        let _ = std::fs::remove_dir_all(&input_dir);
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+}